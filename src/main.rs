@@ -1,13 +1,19 @@
 use async_std::fs;
+use async_std::prelude::*;
 use async_std::task;
 use clap::{App, AppSettings, Arg};
 use futures::stream::{FuturesUnordered, StreamExt};
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
 use indoc::indoc;
-use rayon::prelude::*;
 use rustywind::options::{Options, WriteMode};
+use serde::Deserialize;
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
 
+const CONFIG_FILE_NAMES: &[&str] = &["rustywind.toml", ".rustywindrc"];
+
 #[async_std::main]
 async fn main() {
     let matches = App::new("RustyWind")
@@ -19,14 +25,23 @@ async fn main() {
         Run rustywind with a path to get a list of files that will be changed
               rustywind . --dry-run
 
+            Directories are walked respecting .gitignore, use --exclude and --extension to narrow the search
+              rustywind . --extension html --exclude \"**/vendor/**\"
+
+            Custom sort order and defaults can be set in a rustywind.toml, CLI flags always win
+              rustywind --config-path ./rustywind.toml .
+
             If you want to reorganize all classes in place, and change the files run with the `--write` flag
               rustywind --write .
-                         
+
+            If you want to pipe a single file's contents through rustywind, pass `-` as the path
+              cat index.html | rustywind - > sorted.html
+
             rustywind [FLAGS] <PATH>"))
         .arg(
             Arg::with_name("file_or_dir")
                 .value_name("PATH")
-                .help("A file or directory to run on")
+                .help("A file or directory to run on, pass - to read from stdin and write to stdout")
                 .index(1)
                 .required(true)
                 .takes_value(true),
@@ -48,9 +63,67 @@ async fn main() {
                 .long("allow-duplicates")
                 .help("When set, rustywind will not delete duplicated classes"),
         )
+        .arg(
+            Arg::with_name("check")
+                .long("check")
+                .conflicts_with("write")
+                .conflicts_with("dry_run")
+                .help(
+                    "Checks if any files contain unsorted classes, \
+                     exits with 1 and prints the files if so, used for CI",
+                ),
+        )
+        .arg(
+            Arg::with_name("diff")
+                .long("diff")
+                .conflicts_with("write")
+                .conflicts_with("dry_run")
+                .conflicts_with("check")
+                .help("Prints a unified diff of the classes that would be sorted"),
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .value_name("GLOB")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true)
+                .help("Glob of files that should be excluded, can be used multiple times"),
+        )
+        .arg(
+            Arg::with_name("extension")
+                .long("extension")
+                .value_name("EXT")
+                .multiple(true)
+                .number_of_values(1)
+                .takes_value(true)
+                .help(
+                    "Only look at files with this extension, can be used multiple times, \
+                     e.g. --extension html --extension jsx",
+                ),
+        )
+        .arg(
+            Arg::with_name("changed")
+                .long("changed")
+                .help(
+                    "Only run on files that git reports as modified or untracked, \
+                     falls back to the full file list when not in a git work tree",
+                ),
+        )
+        .arg(
+            Arg::with_name("config_path")
+                .long("config-path")
+                .value_name("PATH")
+                .takes_value(true)
+                .help(
+                    "Path to a rustywind.toml or .rustywindrc config file, \
+                     by default rustywind looks for one in PATH and its parent directories",
+                ),
+        )
         .get_matches();
 
-    let options = Options::new_from_matches(&matches);
+    let mut options = Options::new_from_matches(&matches);
+    apply_config_file(&matches, &mut options);
 
     match &options.write_mode {
         WriteMode::DryRun => println!(
@@ -65,30 +138,313 @@ async fn main() {
         WriteMode::ToConsole => println!(
             "\nprinting file contents to console, run with --write to save changes to files:"
         ),
+
+        WriteMode::Check => println!(
+            "\nchecking if any files need to be reorganized, this will not modify any files:"
+        ),
+
+        WriteMode::Diff => (),
+
+        // stdin is piped straight to stdout, so we keep it free of status banners
+        WriteMode::Stdin => return run_from_stdin(&options).await,
+    }
+
+    let exclude_globs = values_of_owned(&matches, "exclude");
+    let extensions = values_of_owned(&matches, "extension");
+    let mut search_paths = collect_search_paths(&options, &exclude_globs, &extensions);
+
+    if matches.is_present("changed") {
+        search_paths = filter_to_changed_files(&options.starting_path, search_paths);
+    }
+
+    let mut tasks = search_paths
+        .iter()
+        .map(|file_path| run_on_file_paths(file_path, &options))
+        .collect::<FuturesUnordered<_>>();
+
+    let mut would_change = false;
+    while let Some(has_changed) = tasks.next().await {
+        would_change = would_change || has_changed;
+    }
+
+    if let WriteMode::Check = &options.write_mode {
+        if would_change {
+            std::process::exit(1);
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(rename = "class-order")]
+    class_order: Option<Vec<String>>,
+    #[serde(rename = "allow-duplicates")]
+    allow_duplicates: Option<bool>,
+    #[serde(rename = "write-mode")]
+    write_mode: Option<String>,
+}
+
+fn apply_config_file(matches: &clap::ArgMatches, options: &mut Options) {
+    let config_path = match find_config_path(matches, &options.starting_path) {
+        Some(config_path) => config_path,
+        None => return,
+    };
+
+    let config = load_config_file(&config_path);
+
+    options.class_order = merge_class_order(options.class_order.clone(), config.class_order);
+
+    options.allow_duplicates = merge_allow_duplicates(
+        matches.is_present("allow-duplicates"),
+        options.allow_duplicates,
+        config.allow_duplicates,
+    );
+
+    options.write_mode = merge_write_mode(
+        cli_picked_write_mode(matches),
+        options.write_mode.clone(),
+        config.write_mode,
+    );
+}
+
+fn cli_picked_write_mode(matches: &clap::ArgMatches) -> bool {
+    matches.is_present("write")
+        || matches.is_present("dry_run")
+        || matches.is_present("check")
+        || matches.is_present("diff")
+        || matches.value_of("file_or_dir") == Some("-")
+}
+
+fn merge_class_order(
+    current: Option<Vec<String>>,
+    from_config: Option<Vec<String>>,
+) -> Option<Vec<String>> {
+    current.or(from_config)
+}
+
+fn merge_allow_duplicates(cli_set: bool, current: bool, from_config: Option<bool>) -> bool {
+    if cli_set {
+        current
+    } else {
+        from_config.unwrap_or(current)
+    }
+}
+
+fn merge_write_mode(cli_picked: bool, current: WriteMode, from_config: Option<String>) -> WriteMode {
+    if cli_picked {
+        return current;
+    }
+
+    from_config
+        .and_then(|write_mode| parse_write_mode(&write_mode))
+        .unwrap_or(current)
+}
+
+fn find_config_path(matches: &clap::ArgMatches, starting_path: &Path) -> Option<PathBuf> {
+    if let Some(config_path) = matches.value_of("config_path") {
+        return Some(PathBuf::from(config_path));
+    }
+
+    let mut dir = if starting_path.is_dir() {
+        Some(starting_path.to_path_buf())
+    } else {
+        starting_path.parent().map(Path::to_path_buf)
+    };
+
+    while let Some(current_dir) = dir {
+        for config_file_name in CONFIG_FILE_NAMES {
+            let candidate = current_dir.join(config_file_name);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+
+        dir = current_dir.parent().map(Path::to_path_buf);
+    }
+
+    None
+}
+
+fn load_config_file(config_path: &Path) -> ConfigFile {
+    let contents = std::fs::read_to_string(config_path).unwrap_or_else(|err| {
+        eprintln!(
+            "\nWarning: failed to read config file {}: {}",
+            config_path.display(),
+            err
+        );
+        String::new()
+    });
+
+    toml::from_str(&contents).unwrap_or_else(|err| {
+        eprintln!(
+            "\nWarning: failed to parse config file {}: {}",
+            config_path.display(),
+            err
+        );
+        ConfigFile::default()
+    })
+}
+
+fn parse_write_mode(write_mode: &str) -> Option<WriteMode> {
+    match write_mode {
+        "write" => Some(WriteMode::ToFile),
+        "dry-run" => Some(WriteMode::DryRun),
+        "console" => Some(WriteMode::ToConsole),
+        "check" => Some(WriteMode::Check),
+        "diff" => Some(WriteMode::Diff),
+        _ => None,
+    }
+}
+
+fn values_of_owned(matches: &clap::ArgMatches, name: &str) -> Vec<String> {
+    matches
+        .values_of(name)
+        .map(|values| values.map(String::from).collect())
+        .unwrap_or_default()
+}
+
+fn collect_search_paths(
+    options: &Options,
+    exclude_globs: &[String],
+    extensions: &[String],
+) -> Vec<PathBuf> {
+    let mut builder = WalkBuilder::new(&options.starting_path);
+
+    if !exclude_globs.is_empty() {
+        let mut overrides = OverrideBuilder::new(&options.starting_path);
+        for glob in exclude_globs {
+            if let Err(err) = overrides.add(&format!("!{}", glob)) {
+                eprintln!("\nError: invalid --exclude glob `{}`: {}", glob, err);
+                std::process::exit(1);
+            }
+        }
+
+        let overrides = overrides.build().unwrap_or_else(|err| {
+            eprintln!("\nError: failed to build --exclude overrides: {}", err);
+            std::process::exit(1);
+        });
+
+        builder.overrides(overrides);
     }
-    options
-        .search_paths
-        .par_iter()
-        .map(|&file_path| async {
-            run_on_file_paths(&file_path, &options).await;
+
+    builder
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().map_or(false, |file_type| file_type.is_file()))
+        .map(ignore::DirEntry::into_path)
+        .filter(|path| {
+            extensions.is_empty()
+                || path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .map_or(false, |ext| extensions.iter().any(|wanted| wanted == ext))
         })
-        .collect::<Vec<_>>()
+        .map(|path| canonical(&path))
+        .collect()
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn filter_to_changed_files(starting_path: &Path, search_paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    match git_modified_files(starting_path) {
+        Some(changed) => search_paths
+            .into_iter()
+            .filter(|path| changed.contains(path))
+            .collect(),
+        None => {
+            eprintln!(
+                "\nWarning: --changed was passed but {} is not inside a git work tree, \
+                 falling back to the full file list",
+                starting_path.display()
+            );
+            search_paths
+        }
+    }
+}
+
+fn git_modified_files(starting_path: &Path) -> Option<HashSet<PathBuf>> {
+    let output = std::process::Command::new("git")
+        .args(&[
+            "status",
+            "--porcelain",
+            "--no-renames",
+            "--untracked-files=all",
+        ])
+        .current_dir(starting_path)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    // porcelain paths are relative to the repository root, not to starting_path
+    let toplevel = std::process::Command::new("git")
+        .args(&["rev-parse", "--show-toplevel"])
+        .current_dir(starting_path)
+        .output()
+        .ok()?;
+
+    if !toplevel.status.success() {
+        return None;
+    }
+
+    let repo_root = PathBuf::from(String::from_utf8_lossy(&toplevel.stdout).trim_end());
+
+    let changed = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.get(3..))
+        .map(|relative_path| canonical(&repo_root.join(relative_path)))
+        .collect();
+
+    Some(changed)
 }
 
-async fn run_on_file_paths(file_path: Path, options: &Options) {
+async fn run_from_stdin(options: &Options) {
+    let mut contents = String::new();
+
+    if let Err(err) = async_std::io::stdin().read_to_string(&mut contents).await {
+        eprintln!("\nError: {:?}", err);
+        eprintln!("Unable to read file contents from stdin");
+        std::process::exit(1);
+    }
+
+    let sorted_content = rustywind::sort_file_contents(contents, options);
+    print!("{}", sorted_content);
+}
+
+async fn run_on_file_paths(file_path: &Path, options: &Options) -> bool {
     match fs::read_to_string(file_path).await {
         Ok(contents) => {
             if rustywind::has_classes(&contents) {
-                let sorted_content = rustywind::sort_file_contents(contents, options);
+                let sorted_content = rustywind::sort_file_contents(contents.clone(), options);
+                let has_changed = sorted_content != contents;
 
                 match &options.write_mode {
                     WriteMode::DryRun => print_file_name(file_path, options),
                     WriteMode::ToFile => write_to_file(file_path, &sorted_content, options).await,
                     WriteMode::ToConsole => print_file_contents(&sorted_content),
+                    WriteMode::Check => {
+                        if has_changed {
+                            print_file_name(file_path, options);
+                        }
+                    }
+                    WriteMode::Diff => {
+                        if has_changed {
+                            print_diff(file_path, &contents, &sorted_content, options);
+                        }
+                    }
+                    WriteMode::Stdin => unreachable!("stdin mode never walks file paths"),
                 }
+
+                has_changed
+            } else {
+                false
             }
         }
-        Err(_error) => (),
+        Err(_error) => false,
     }
 }
 
@@ -120,3 +476,174 @@ fn get_file_name(file_path: &Path, dir: &Path) -> String {
 fn print_file_contents(file_contents: &str) {
     println!("\n\n{}\n\n", file_contents)
 }
+
+fn print_diff(file_path: &Path, original: &str, sorted: &str, options: &Options) {
+    use similar::{ChangeTag, TextDiff};
+
+    let file_name = get_file_name(file_path, &options.starting_path);
+    let diff = TextDiff::from_lines(original, sorted);
+
+    println!("\n--- {}", file_name);
+    println!("+++ {}", file_name);
+
+    for hunk in diff.unified_diff().iter_hunks() {
+        println!("{}", hunk.header());
+
+        for change in hunk.iter_changes() {
+            let sign = match change.tag() {
+                ChangeTag::Delete => "-",
+                ChangeTag::Insert => "+",
+                ChangeTag::Equal => " ",
+            };
+
+            print!("{}{}", sign, change);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!(
+            "rustywind-test-{}-{}-{}",
+            name,
+            std::process::id(),
+            nonce
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn find_config_path_walks_up_parent_directories() {
+        let base = temp_dir("find-config-path");
+        let nested = base.join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(base.join("rustywind.toml"), "").unwrap();
+
+        let matches = App::new("test").get_matches_from(Vec::<String>::new());
+        let found = find_config_path(&matches, &nested);
+
+        assert_eq!(found, Some(base.join("rustywind.toml")));
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn find_config_path_prefers_explicit_config_path_flag() {
+        let base = temp_dir("find-config-path-explicit");
+        let explicit = base.join("custom.toml");
+        std::fs::write(&explicit, "").unwrap();
+
+        let matches = App::new("test")
+            .arg(
+                Arg::with_name("config_path")
+                    .long("config-path")
+                    .takes_value(true),
+            )
+            .get_matches_from(vec!["test", "--config-path", explicit.to_str().unwrap()]);
+
+        assert_eq!(find_config_path(&matches, &base), Some(explicit));
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn merge_class_order_prefers_cli_value() {
+        let cli = Some(vec!["flex".to_string()]);
+        let config = Some(vec!["block".to_string()]);
+
+        assert_eq!(merge_class_order(cli.clone(), config), cli);
+    }
+
+    #[test]
+    fn merge_class_order_falls_back_to_config() {
+        let config = Some(vec!["block".to_string()]);
+
+        assert_eq!(merge_class_order(None, config.clone()), config);
+    }
+
+    #[test]
+    fn merge_allow_duplicates_keeps_cli_value_when_set() {
+        assert!(merge_allow_duplicates(true, true, Some(false)));
+    }
+
+    #[test]
+    fn merge_allow_duplicates_uses_config_when_cli_unset() {
+        assert!(merge_allow_duplicates(false, false, Some(true)));
+    }
+
+    #[test]
+    fn merge_write_mode_keeps_cli_value_when_picked() {
+        assert_eq!(
+            merge_write_mode(true, WriteMode::ToFile, Some("check".into())),
+            WriteMode::ToFile
+        );
+    }
+
+    #[test]
+    fn merge_write_mode_uses_config_when_cli_unset() {
+        assert_eq!(
+            merge_write_mode(false, WriteMode::ToFile, Some("check".into())),
+            WriteMode::Check
+        );
+    }
+
+    #[test]
+    fn merge_write_mode_ignores_unknown_config_value() {
+        assert_eq!(
+            merge_write_mode(false, WriteMode::ToFile, Some("bogus".into())),
+            WriteMode::ToFile
+        );
+    }
+
+    fn run_git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .expect("failed to run git");
+        assert!(status.success());
+    }
+
+    #[test]
+    fn git_modified_files_includes_files_in_untracked_directories() {
+        let repo = temp_dir("git-modified-files");
+        run_git(&repo, &["init", "--quiet"]);
+        run_git(&repo, &["config", "user.email", "test@example.com"]);
+        run_git(&repo, &["config", "user.name", "Test"]);
+
+        let new_dir = repo.join("newdir");
+        std::fs::create_dir_all(&new_dir).unwrap();
+        std::fs::write(new_dir.join("a.txt"), "a").unwrap();
+        std::fs::write(new_dir.join("b.txt"), "b").unwrap();
+
+        let changed = git_modified_files(&repo).expect("repo should be a git work tree");
+
+        assert!(changed.contains(&canonical(&new_dir.join("a.txt"))));
+        assert!(changed.contains(&canonical(&new_dir.join("b.txt"))));
+
+        std::fs::remove_dir_all(&repo).unwrap();
+    }
+
+    #[test]
+    fn filter_to_changed_files_falls_back_outside_a_git_work_tree() {
+        let dir = temp_dir("not-a-git-repo");
+        let file = dir.join("a.html");
+        std::fs::write(&file, "").unwrap();
+
+        let search_paths = vec![canonical(&file)];
+        let filtered = filter_to_changed_files(&dir, search_paths.clone());
+
+        assert_eq!(filtered, search_paths);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}